@@ -7,10 +7,16 @@ use ansi_term::{
     Colour::{Green, Red, White, Yellow},
     Style,
 };
-use clap::Parser;
+use clap::{Parser, ValueEnum};
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
+use nix::poll::{poll, PollFd, PollFlags};
 use nix::unistd::Uid;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::io::{self, ErrorKind, IsTerminal};
+use std::os::unix::io::AsRawFd;
 use std::process;
+use std::time::{Duration, Instant};
 
 use tbtools::{
     self,
@@ -18,6 +24,18 @@ use tbtools::{
     util, Address, Device,
 };
 
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    /// Human readable, optionally colored, text.
+    Text,
+    /// Comma-separated values suitable for scripting (the former `--script`).
+    Script,
+    /// A single JSON array of adapter objects.
+    Json,
+}
+
 #[derive(Parser, Debug)]
 #[command(version)]
 #[command(about = "Dump Thunderbolt/USB4 router adapter states", long_about = None)]
@@ -31,13 +49,60 @@ struct Args {
     /// Select only specific adapters
     #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..64))]
     adapter: Option<Vec<u16>>,
-    /// Output suitable for scripting
-    #[arg(short = 'S', long)]
-    script: bool,
+    /// Output format
+    #[arg(short = 'f', long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Comma-separated predicates to select adapters, e.g. "type=pcie,state=disabled"
+    #[arg(long, value_delimiter = ',', value_parser = parse_predicate)]
+    filter: Option<Vec<Predicate>>,
+    /// Keep running and print adapter state transitions as they happen
+    #[arg(short, long)]
+    watch: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Predicate {
+    Type(String),
+    State(String),
+    Lane,
+    Upstream,
+}
+
+fn parse_predicate(s: &str) -> Result<Predicate, String> {
+    match s.split_once('=') {
+        Some(("type", value)) => Ok(Predicate::Type(value.to_lowercase())),
+        Some(("state", value)) => Ok(Predicate::State(value.to_lowercase())),
+        Some((field, _)) => Err(format!("unknown filter field: {}", field)),
+        None => match s {
+            "lane" => Ok(Predicate::Lane),
+            "upstream" => Ok(Predicate::Upstream),
+            _ => Err(format!("unknown filter predicate: {}", s)),
+        },
+    }
+}
+
+fn predicate_matches(predicate: &Predicate, adapter: &Adapter) -> bool {
+    match predicate {
+        Predicate::Type(value) => adapter.kind().to_string().to_lowercase().contains(value),
+        Predicate::State(value) => {
+            state_name(adapter.state()).to_lowercase() == *value
+                || (adapter.state() == State::Enabled
+                    && protocol_state(adapter).0.to_lowercase() == *value)
+        }
+        Predicate::Lane => adapter.is_lane(),
+        Predicate::Upstream => adapter.is_upstream(),
+    }
+}
+
+fn adapter_matches(adapter: &Adapter, args: &Args) -> bool {
+    match &args.filter {
+        Some(predicates) => predicates.iter().all(|p| predicate_matches(p, adapter)),
+        None => true,
+    }
 }
 
 fn dump_adapter_num(adapter_num: u16, args: &Args) {
-    if args.script {
+    if args.format == Format::Script {
         print!("{},", adapter_num);
     } else if io::stdout().is_terminal() {
         print!("{}: ", White.bold().paint(format!("{:>2}", adapter_num)));
@@ -47,19 +112,9 @@ fn dump_adapter_num(adapter_num: u16, args: &Args) {
 }
 
 fn dump_adapter_type(adapter: &Adapter, args: &Args) {
-    let mut kind: String = if adapter.is_lane0() {
-        String::from("Lane 0")
-    } else if adapter.is_lane1() {
-        String::from("Lane 1")
-    } else {
-        adapter.kind().to_string()
-    };
-
-    if adapter.is_upstream() {
-        kind.push_str(" (upstream)");
-    }
+    let kind = adapter_label(adapter);
 
-    if args.script {
+    if args.format == Format::Script {
         print!("{},", kind);
     } else {
         print!("{:<30}", kind);
@@ -102,14 +157,57 @@ fn protocol_state(adapter: &Adapter) -> (&str, Style) {
             }
         }
 
+        Type::DpIn | Type::DpOut => {
+            let (video, aux) = match adapter.register_by_name("ADP_DP_CS_0") {
+                Some(reg) => (
+                    reg.field_by_name("VE").is_some_and(|f| reg.field_value(f) != 0),
+                    reg.field_by_name("AE").is_some_and(|f| reg.field_value(f) != 0),
+                ),
+                None => (false, false),
+            };
+            let hpd = field_is_set(adapter, "ADP_DP_CS_2", "HPD");
+
+            return match (video, aux, hpd) {
+                (true, _, true) => ("Video + HPD", Green.normal()),
+                (true, _, false) => ("Video", Green.normal()),
+                (false, true, _) => ("AUX only", Yellow.normal()),
+                (false, false, true) => ("HPD", Yellow.normal()),
+                (false, false, false) => ("Disabled", Red.normal()),
+            };
+        }
+
+        Type::Usb4Port => {
+            let link_up = field_is_set(adapter, "ADP_USB4_CS_2", "ULSHC");
+            let hpd = field_is_set(adapter, "ADP_USB4_CS_2", "HPD");
+            let source = field_is_set(adapter, "ADP_USB4_CS_2", "SRC");
+
+            return match (link_up, hpd, source) {
+                (true, true, true) => ("Link up + HPD (source)", Green.normal()),
+                (true, true, false) => ("Link up + HPD (sink)", Green.normal()),
+                (true, false, true) => ("Link up (source)", Green.normal()),
+                (true, false, false) => ("Link up (sink)", Green.normal()),
+                (false, true, true) => ("HPD (source)", Yellow.normal()),
+                (false, true, false) => ("HPD (sink)", Yellow.normal()),
+                (false, false, true) => ("Link down (source)", Red.normal()),
+                (false, false, false) => ("Link down (sink)", Red.normal()),
+            };
+        }
+
         _ => (),
     }
 
     ("Enabled", Green.normal())
 }
 
-fn dump_adapter_state(adapter: &Adapter, args: &Args) {
-    let (name, style) = match adapter.state() {
+fn field_is_set(adapter: &Adapter, register: &str, field: &str) -> bool {
+    adapter
+        .register_by_name(register)
+        .and_then(|reg| reg.field_by_name(field).map(|field| reg.field_value(field)))
+        .is_some_and(|v| v != 0)
+}
+
+fn adapter_status(adapter: &Adapter) -> (&str, Style) {
+    match adapter.state() {
         State::Disabled => ("Disabled", Red.normal()),
         State::Enabled => protocol_state(adapter),
         State::Training => ("Training/Bonding", Yellow.normal()),
@@ -120,9 +218,13 @@ fn dump_adapter_state(adapter: &Adapter, args: &Args) {
         State::Cl2 => ("CL2", Green.bold()),
         State::Cld => ("CLd", Red.normal()),
         _ => ("Unknown", White.dimmed()),
-    };
+    }
+}
+
+fn dump_adapter_state(adapter: &Adapter, args: &Args) {
+    let (name, style) = adapter_status(adapter);
 
-    if args.script {
+    if args.format == Format::Script {
         print!("{}", name);
     } else if io::stdout().is_terminal() {
         print!("{}", style.paint(format!("{:<10}", name)));
@@ -131,41 +233,105 @@ fn dump_adapter_state(adapter: &Adapter, args: &Args) {
     }
 }
 
-fn dump_other(args: &Args) {
-    print!("Not implemented");
+fn dump_adapter(adapter: &Adapter, args: &Args) {
+    dump_adapter_num(adapter.adapter(), args);
+    dump_adapter_type(adapter, args);
+    dump_adapter_state(adapter, args);
+
+    println!();
+}
 
-    if args.script {
-        print!(",");
+fn print_header(args: &Args) {
+    if args.format == Format::Script {
+        println!("adapter,type,state");
     }
 }
 
-fn dump_adapter(adapter: &Adapter, args: &Args) {
-    dump_adapter_num(adapter.adapter(), args);
+fn state_name(state: State) -> &'static str {
+    match state {
+        State::Disabled => "Disabled",
+        State::Enabled => "Enabled",
+        State::Training => "Training",
+        State::Cl0 => "CL0",
+        State::Cl0sTx => "CL0s Tx",
+        State::Cl0sRx => "CL0s Rx",
+        State::Cl1 => "CL1",
+        State::Cl2 => "CL2",
+        State::Cld => "CLd",
+        _ => "Unknown",
+    }
+}
+
+#[derive(Serialize)]
+struct AdapterJson {
+    adapter: u16,
+    kind: String,
+    lane: bool,
+    upstream: bool,
+    state: String,
+    protocol_state: String,
+}
 
-    if adapter.is_lane() || adapter.is_protocol() {
-        dump_adapter_type(adapter, args);
-        dump_adapter_state(adapter, args);
+fn adapter_json(adapter: &Adapter) -> AdapterJson {
+    let protocol_state = if adapter.state() == State::Enabled {
+        protocol_state(adapter).0.to_string()
     } else {
-        dump_other(args);
-    }
+        String::new()
+    };
 
-    println!();
+    AdapterJson {
+        adapter: adapter.adapter(),
+        kind: adapter.kind().to_string(),
+        lane: adapter.is_lane(),
+        upstream: adapter.is_upstream(),
+        state: state_name(adapter.state()).to_string(),
+        protocol_state,
+    }
 }
 
-fn print_header(args: &Args) {
-    if args.script {
-        println!("adapter,type,state");
+fn dump_adapters_json(device: &mut Device, args: &Args) -> io::Result<()> {
+    device.read_adapters()?;
+
+    let mut adapters_json = Vec::new();
+
+    if let Some(adapter_numbers) = &args.adapter {
+        for adapter_num in adapter_numbers {
+            if let Some(adapter) = device.adapter(*adapter_num) {
+                if adapter_matches(adapter, args) {
+                    adapters_json.push(adapter_json(adapter));
+                }
+            } else {
+                eprintln!("Warning: non-existing adapter: {}!", *adapter_num);
+            }
+        }
+    } else if let Some(adapters) = device.adapters() {
+        for adapter in adapters {
+            if adapter_matches(adapter, args) {
+                adapters_json.push(adapter_json(adapter));
+            }
+        }
     }
+
+    let json = serde_json::to_string(&adapters_json).map_err(io::Error::other)?;
+    println!("{}", json);
+
+    Ok(())
 }
 
 fn dump_adapters(device: &mut Device, args: &Args) -> io::Result<()> {
+    if args.format == Format::Json {
+        return dump_adapters_json(device, args);
+    }
+
     device.read_adapters()?;
 
     if let Some(adapter_numbers) = &args.adapter {
         print_header(args);
         for adapter_num in adapter_numbers {
             if let Some(adapter) = device.adapter(*adapter_num) {
-                dump_adapter(adapter, args);
+                if adapter_matches(adapter, args) {
+                    dump_adapter(adapter, args);
+                }
             } else {
                 eprintln!("Warning: non-existing adapter: {}!", *adapter_num);
             }
@@ -173,13 +339,187 @@ fn dump_adapters(device: &mut Device, args: &Args) -> io::Result<()> {
     } else if let Some(adapters) = device.adapters() {
         print_header(args);
         for adapter in adapters {
-            dump_adapter(adapter, args);
+            if adapter_matches(adapter, args) {
+                dump_adapter(adapter, args);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn adapter_label(adapter: &Adapter) -> String {
+    let mut kind: String = if adapter.is_lane0() {
+        String::from("Lane 0")
+    } else if adapter.is_lane1() {
+        String::from("Lane 1")
+    } else {
+        adapter.kind().to_string()
+    };
+
+    if adapter.is_upstream() {
+        kind.push_str(" (upstream)");
+    }
+
+    kind
+}
+
+fn adapter_snapshot(adapter: &Adapter) -> (State, String) {
+    let sub = match adapter.state() {
+        State::Enabled => protocol_state(adapter).0.to_string(),
+        _ => String::new(),
+    };
+
+    (adapter.state(), sub)
+}
+
+fn transition_name(snapshot: &(State, String)) -> String {
+    let (state, sub) = snapshot;
+
+    match state {
+        State::Disabled => "Disabled".to_string(),
+        State::Enabled => sub.clone(),
+        State::Training => "Training".to_string(),
+        State::Cl0 => "CL0".to_string(),
+        State::Cl0sTx => "CL0s Tx".to_string(),
+        State::Cl0sRx => "CL0s Rx".to_string(),
+        State::Cl1 => "CL1".to_string(),
+        State::Cl2 => "CL2".to_string(),
+        State::Cld => "CLd".to_string(),
+        _ => "Unknown".to_string(),
+    }
+}
+
+fn watch_timestamp(start: Instant) -> String {
+    let elapsed = start.elapsed();
+    format!("[{:>6}.{:03}]", elapsed.as_secs(), elapsed.subsec_millis())
+}
+
+fn watch_tick(
+    device: &mut Device,
+    args: &Args,
+    cache: &mut BTreeMap<u16, (State, String)>,
+    start: Instant,
+    priming: bool,
+) -> io::Result<()> {
+    device.read_adapters()?;
+
+    let Some(adapters) = device.adapters() else {
+        return Ok(());
+    };
+
+    for adapter in adapters {
+        let num = adapter.adapter();
+
+        if let Some(adapter_numbers) = &args.adapter {
+            if !adapter_numbers.contains(&num) {
+                continue;
+            }
+        }
+
+        if !adapter_matches(adapter, args) {
+            continue;
+        }
+
+        let snapshot = adapter_snapshot(adapter);
+        let previous = cache.insert(num, snapshot.clone());
+
+        let changed = match &previous {
+            Some(previous) => *previous != snapshot,
+            None => true,
+        };
+
+        if !changed {
+            continue;
         }
+
+        let to = transition_name(&snapshot);
+
+        let status = if priming {
+            if io::stdout().is_terminal() {
+                Green.bold().paint(&to).to_string()
+            } else {
+                to.clone()
+            }
+        } else {
+            let from = previous.as_ref().map(transition_name).unwrap_or_else(|| to.clone());
+
+            if io::stdout().is_terminal() {
+                format!(
+                    "{} {} {}",
+                    Yellow.paint(&from),
+                    "\u{2192}",
+                    Green.bold().paint(&to)
+                )
+            } else {
+                format!("{} -> {}", from, to)
+            }
+        };
+
+        println!(
+            "{} {:>2}: {:<14} {}",
+            watch_timestamp(start),
+            num,
+            adapter_label(adapter),
+            status
+        );
     }
 
     Ok(())
 }
 
+fn wait_for_event(monitor: Option<&udev::MonitorSocket>, syspath: &std::path::Path) -> bool {
+    let Some(monitor) = monitor else {
+        std::thread::sleep(WATCH_POLL_INTERVAL);
+        return true;
+    };
+
+    let fd = PollFd::new(monitor, PollFlags::POLLIN);
+    if poll(&mut [fd], -1).is_err() {
+        return false;
+    }
+
+    monitor.iter().any(|event| event.syspath().starts_with(syspath))
+}
+
+fn open_monitor() -> Option<udev::MonitorSocket> {
+    let monitor = udev::MonitorBuilder::new()
+        .and_then(|b| b.match_subsystem("thunderbolt"))
+        .and_then(|b| b.listen());
+
+    let monitor = match monitor {
+        Ok(monitor) => monitor,
+        Err(err) => {
+            eprintln!("Warning: failed to open udev monitor: {}", err);
+            eprintln!("Warning: falling back to polling every {:?}", WATCH_POLL_INTERVAL);
+            return None;
+        }
+    };
+
+    if let Err(err) = fcntl(monitor.as_raw_fd(), FcntlArg::F_SETFL(OFlag::O_NONBLOCK)) {
+        eprintln!("Warning: failed to set udev monitor non-blocking: {}", err);
+    }
+
+    Some(monitor)
+}
+
+fn watch_adapters(device: &mut Device, args: &Args) -> io::Result<()> {
+    let start = Instant::now();
+    let mut cache = BTreeMap::new();
+    let monitor = open_monitor();
+    let syspath = device.syspath();
+
+    // Prime the cache and print the initial state before waiting for
+    // transitions.
+    watch_tick(device, args, &mut cache, start, true)?;
+
+    loop {
+        if wait_for_event(monitor.as_ref(), &syspath) {
+            watch_tick(device, args, &mut cache, start, false)?;
+        }
+    }
+}
+
 fn dump(args: &Args) -> io::Result<()> {
     let address = Address::Router {
         domain: args.domain,
@@ -193,9 +533,11 @@ fn dump(args: &Args) -> io::Result<()> {
         }
     };
 
-    dump_adapters(&mut device, args)?;
-
-    Ok(())
+    if args.watch {
+        watch_adapters(&mut device, args)
+    } else {
+        dump_adapters(&mut device, args)
+    }
 }
 
 fn main() {